@@ -0,0 +1,336 @@
+use crate::{coulomb_force, magnitude, subtract, Particle};
+
+/// Opening angle used to decide when an octree node is "far enough" to be
+/// treated as a single pseudo-particle. Smaller is more accurate (and
+/// closer to the brute-force O(n^2) result) but slower.
+pub const DEFAULT_THETA: f32 = 0.5;
+
+/// Depth at which `insert` gives up subdividing and merges into the
+/// existing leaf instead. `child_bounds` halves `half_width` every level, so
+/// two particles at (or extremely close to) the same position would
+/// otherwise recurse until `half_width` underflows to zero and never
+/// separate into different octants — this cap turns that infinite descent
+/// into a single merged pseudo-particle. 32 levels of halving is already far
+/// finer than `f32` position precision can distinguish.
+const MAX_DEPTH: u32 = 32;
+
+#[derive(Clone, Copy)]
+struct Bounds {
+    center: [f32; 3],
+    half_width: f32,
+}
+
+impl Bounds {
+    fn octant_of(&self, position: [f32; 3]) -> usize {
+        let mut octant = 0;
+        for axis in 0..3 {
+            if position[axis] >= self.center[axis] {
+                octant |= 1 << axis;
+            }
+        }
+        octant
+    }
+
+    fn child_bounds(&self, octant: usize) -> Bounds {
+        let half_width = self.half_width / 2.0;
+        let mut center = self.center;
+        for axis in 0..3 {
+            center[axis] += if octant & (1 << axis) == 0 { -half_width } else { half_width };
+        }
+        Bounds { center, half_width }
+    }
+}
+
+enum Node {
+    Empty,
+    Leaf {
+        position: [f32; 3],
+        charge: f32,
+    },
+    Internal {
+        /// Sum of the charge of every particle under this node. Can be used
+        /// as the "mass" of the aggregate pseudo-particle when approximating
+        /// the force it exerts.
+        total_charge: f32,
+        /// Charge-weighted center of the particles under this node (the
+        /// "center of charge", analogous to a center of mass).
+        center_of_charge: [f32; 3],
+        bounds: Bounds,
+        children: Box<[Node; 8]>,
+    },
+}
+
+/// An octree over a 3D particle cloud used to approximate the Coulomb
+/// force via the Barnes-Hut algorithm: distant clusters of particles are
+/// summarized as a single pseudo-particle at their center of charge,
+/// turning the all-pairs O(n^2) sum into an O(n log n) tree walk.
+pub struct BarnesHutTree {
+    root: Node,
+    theta: f32,
+    eps: f32,
+}
+
+impl BarnesHutTree {
+    /// Builds a fresh tree over `particles`, sized to their bounding box.
+    pub fn build(particles: &[Particle], theta: f32, eps: f32) -> Self {
+        let bounds = bounding_cube(particles);
+        let mut root = Node::Empty;
+        for particle in particles {
+            insert(&mut root, bounds, particle.position, particle.charge, 0);
+        }
+        BarnesHutTree { root, theta, eps }
+    }
+
+    /// Approximates the net Plummer-softened Coulomb force on `particle`
+    /// from every other particle in the tree, using the same force law as
+    /// `crate::coulomb_force`.
+    pub fn force_on(&self, particle: &Particle) -> [f32; 3] {
+        accumulate_force(&self.root, self.theta, self.eps, particle.position, particle.charge)
+    }
+}
+
+fn bounding_cube(particles: &[Particle]) -> Bounds {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for particle in particles {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(particle.position[axis]);
+            max[axis] = max[axis].max(particle.position[axis]);
+        }
+    }
+    if !min[0].is_finite() {
+        // Empty particle set; bounds don't matter.
+        return Bounds { center: [0.0, 0.0, 0.0], half_width: 1.0 };
+    }
+    let center = [
+        (min[0] + max[0]) / 2.0,
+        (min[1] + max[1]) / 2.0,
+        (min[2] + max[2]) / 2.0,
+    ];
+    let half_width = ((max[0] - min[0]).max(max[1] - min[1]).max(max[2] - min[2]) / 2.0)
+        .max(f32::EPSILON);
+    Bounds { center, half_width }
+}
+
+fn insert(node: &mut Node, bounds: Bounds, position: [f32; 3], charge: f32, depth: u32) {
+    match node {
+        Node::Empty => {
+            *node = Node::Leaf { position, charge };
+        }
+        Node::Leaf {
+            position: existing_position,
+            charge: existing_charge,
+        } => {
+            let existing_position = *existing_position;
+            let existing_charge = *existing_charge;
+            if depth >= MAX_DEPTH {
+                // Too deep to keep subdividing (the two particles are
+                // effectively coincident at `f32` precision): fold the new
+                // charge into this leaf as a single combined pseudo-particle
+                // rather than recursing forever.
+                *node = Node::Leaf {
+                    position: weighted_center(existing_position, existing_charge, position, charge),
+                    charge: existing_charge + charge,
+                };
+                return;
+            }
+            let mut children = new_children();
+            insert(
+                &mut children[bounds.octant_of(existing_position)],
+                bounds.child_bounds(bounds.octant_of(existing_position)),
+                existing_position,
+                existing_charge,
+                depth + 1,
+            );
+            insert(
+                &mut children[bounds.octant_of(position)],
+                bounds.child_bounds(bounds.octant_of(position)),
+                position,
+                charge,
+                depth + 1,
+            );
+            *node = Node::Internal {
+                total_charge: existing_charge + charge,
+                center_of_charge: weighted_center(
+                    existing_position,
+                    existing_charge,
+                    position,
+                    charge,
+                ),
+                bounds,
+                children: Box::new(children),
+            };
+        }
+        Node::Internal {
+            total_charge,
+            center_of_charge,
+            children,
+            ..
+        } => {
+            *center_of_charge = weighted_center(*center_of_charge, *total_charge, position, charge);
+            *total_charge += charge;
+            let octant = bounds.octant_of(position);
+            insert(
+                &mut children[octant],
+                bounds.child_bounds(octant),
+                position,
+                charge,
+                depth + 1,
+            );
+        }
+    }
+}
+
+fn new_children() -> [Node; 8] {
+    [
+        Node::Empty,
+        Node::Empty,
+        Node::Empty,
+        Node::Empty,
+        Node::Empty,
+        Node::Empty,
+        Node::Empty,
+        Node::Empty,
+    ]
+}
+
+fn weighted_center(a: [f32; 3], a_weight: f32, b: [f32; 3], b_weight: f32) -> [f32; 3] {
+    let total = a_weight + b_weight;
+    if total == 0.0 {
+        return a;
+    }
+    [
+        (a[0] * a_weight + b[0] * b_weight) / total,
+        (a[1] * a_weight + b[1] * b_weight) / total,
+        (a[2] * a_weight + b[2] * b_weight) / total,
+    ]
+}
+
+fn accumulate_force(node: &Node, theta: f32, eps: f32, position: [f32; 3], charge: f32) -> [f32; 3] {
+    match node {
+        Node::Empty => [0.0, 0.0, 0.0],
+        Node::Leaf {
+            position: other_position,
+            charge: other_charge,
+        } => {
+            if *other_position == position {
+                [0.0, 0.0, 0.0]
+            } else {
+                coulomb_force(position, charge, *other_position, *other_charge, eps)
+            }
+        }
+        Node::Internal {
+            total_charge,
+            center_of_charge,
+            bounds,
+            children,
+        } => {
+            // The opening-angle test measures distance from the node's
+            // geometric center, not `center_of_charge`: when a node's
+            // members have mixed signs, `total_charge` can be near zero,
+            // which sends the charge-weighted center arbitrarily far
+            // outside the node's bounding cube and makes a node full of
+            // close neighbors look "distant".
+            let r = magnitude(subtract(position, bounds.center));
+            let s = bounds.half_width * 2.0;
+            if r > 0.0 && s / r < theta {
+                coulomb_force(position, charge, *center_of_charge, *total_charge, eps)
+            } else {
+                let mut total = [0.0, 0.0, 0.0];
+                for child in children.iter() {
+                    let contribution = accumulate_force(child, theta, eps, position, charge);
+                    total[0] += contribution[0];
+                    total[1] += contribution[1];
+                    total[2] += contribution[2];
+                }
+                total
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::{Distribution, Uniform};
+
+    const TEST_EPS: f32 = 0.01;
+
+    fn brute_force(particles: &[Particle], on: usize) -> [f32; 3] {
+        let me = &particles[on];
+        let mut total = [0.0, 0.0, 0.0];
+        for (i, other) in particles.iter().enumerate() {
+            if i == on {
+                continue;
+            }
+            let contribution =
+                coulomb_force(me.position, me.charge, other.position, other.charge, TEST_EPS);
+            total[0] += contribution[0];
+            total[1] += contribution[1];
+            total[2] += contribution[2];
+        }
+        total
+    }
+
+    #[test]
+    fn approximation_matches_brute_force_within_tolerance() {
+        let mut rng = rand::thread_rng();
+        let position_dist = Uniform::new(-1.0, 1.0);
+        let charge_dist = Uniform::new(-1.0, 1.0);
+
+        let particles: Vec<Particle> = (0..200)
+            .map(|_| Particle {
+                mass: 1.0,
+                charge: charge_dist.sample(&mut rng),
+                velocity: [0.0, 0.0, 0.0],
+                position: [
+                    position_dist.sample(&mut rng),
+                    position_dist.sample(&mut rng),
+                    position_dist.sample(&mut rng),
+                ],
+                life: f32::INFINITY,
+                acceleration: [0.0, 0.0, 0.0],
+            })
+            .collect();
+
+        let tree = BarnesHutTree::build(&particles, DEFAULT_THETA, TEST_EPS);
+
+        for i in 0..particles.len() {
+            let approx = tree.force_on(&particles[i]);
+            let exact = brute_force(&particles, i);
+            let error = magnitude(subtract(approx, exact));
+            let scale = magnitude(exact).max(1.0);
+            assert!(
+                error / scale < 0.2,
+                "approximate force {:?} too far from exact {:?} for particle {}",
+                approx,
+                exact,
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn build_does_not_overflow_on_coincident_positions() {
+        // Every particle lands on the same point, e.g. two left-clicks
+        // without moving the mouse. Without a depth cap, `insert` would
+        // recurse until `half_width` underflows and never separate the
+        // particles into different octants, overflowing the stack.
+        let particles: Vec<Particle> = (0..50)
+            .map(|i| Particle {
+                mass: 1.0,
+                charge: if i % 2 == 0 { 1.0 } else { -1.0 },
+                velocity: [0.0, 0.0, 0.0],
+                position: [0.3, -0.2, 0.1],
+                life: f32::INFINITY,
+                acceleration: [0.0, 0.0, 0.0],
+            })
+            .collect();
+
+        let tree = BarnesHutTree::build(&particles, DEFAULT_THETA, TEST_EPS);
+        for particle in &particles {
+            let force = tree.force_on(particle);
+            assert_eq!(force, [0.0, 0.0, 0.0]);
+        }
+    }
+}
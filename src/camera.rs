@@ -0,0 +1,64 @@
+use glam::{Mat4, Vec3};
+
+const ORBIT_SPEED: f32 = 0.005;
+const ZOOM_SPEED: f32 = 0.2;
+const MIN_DISTANCE: f32 = 0.5;
+const MAX_DISTANCE: f32 = 20.0;
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// An orbit camera: it always looks at `target` from `distance` away, at
+/// the angle given by `yaw`/`pitch`. A mouse drag (button chosen by the
+/// caller, see `set_dragging`) rotates around the target, and the scroll
+/// wheel dollies in and out.
+pub struct Camera {
+    pub target: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    dragging: bool,
+    last_cursor: Option<(f64, f64)>,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            target: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.3,
+            distance: 3.0,
+            dragging: false,
+            last_cursor: None,
+        }
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        let eye = self.target + self.orbit_offset();
+        Mat4::look_at_rh(eye, self.target, Vec3::Y)
+    }
+
+    fn orbit_offset(&self) -> Vec3 {
+        Vec3::new(
+            self.distance * self.pitch.cos() * self.yaw.sin(),
+            self.distance * self.pitch.sin(),
+            self.distance * self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    pub fn set_dragging(&mut self, dragging: bool) {
+        self.dragging = dragging;
+    }
+
+    pub fn handle_cursor_moved(&mut self, x: f64, y: f64) {
+        if let (true, Some((last_x, last_y))) = (self.dragging, self.last_cursor) {
+            let dx = (x - last_x) as f32;
+            let dy = (y - last_y) as f32;
+            self.yaw -= dx * ORBIT_SPEED;
+            self.pitch = (self.pitch + dy * ORBIT_SPEED).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+        self.last_cursor = Some((x, y));
+    }
+
+    pub fn handle_scroll(&mut self, delta: f32) {
+        self.distance = (self.distance - delta * ZOOM_SPEED).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+}
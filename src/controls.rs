@@ -0,0 +1,55 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// Tracks the pieces of mouse/keyboard state the event loop needs to turn
+/// clicks into particles: where the cursor currently is, and which charge
+/// sign a left-click should spawn (toggled by holding Shift).
+pub struct Controls {
+    cursor: (f64, f64),
+    pub charge_sign: f32,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Controls {
+            cursor: (0.0, 0.0),
+            charge_sign: 1.0,
+        }
+    }
+
+    pub fn handle_cursor_moved(&mut self, x: f64, y: f64) {
+        self.cursor = (x, y);
+    }
+
+    /// Sets the charge sign a left-click will spawn: negative while Shift
+    /// is held, positive otherwise.
+    pub fn set_charge_sign(&mut self, shift_held: bool) {
+        self.charge_sign = if shift_held { -1.0 } else { 1.0 };
+    }
+
+    /// Maps the last known cursor position from window pixels to world
+    /// space, by casting a ray through `inverse_view_proj` (the inverse of
+    /// `perspective * view`) and intersecting it with the `z = plane_z`
+    /// plane. Without this, injected particles would land on the world
+    /// `z = 0` plane regardless of how the orbit camera is currently
+    /// rotated or zoomed, so clicks would stop landing under the cursor as
+    /// soon as the camera moved.
+    pub fn cursor_world(&self, width: u32, height: u32, inverse_view_proj: Mat4, plane_z: f32) -> [f32; 3] {
+        let ndc_x = (2.0 * self.cursor.0 / width.max(1) as f64 - 1.0) as f32;
+        let ndc_y = (1.0 - 2.0 * self.cursor.1 / height.max(1) as f64) as f32;
+
+        let unproject = |ndc_z: f32| -> Vec3 {
+            let clip = inverse_view_proj * Vec4::new(ndc_x, ndc_y, ndc_z, 1.0);
+            clip.truncate() / clip.w
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        let direction = far - near;
+        let t = if direction.z.abs() > f32::EPSILON {
+            (plane_z - near.z) / direction.z
+        } else {
+            0.0
+        };
+        let world = near + direction * t;
+        [world.x, world.y, world.z]
+    }
+}
@@ -0,0 +1,84 @@
+use crate::Particle;
+use rand::distributions::{Distribution, Normal, Uniform};
+use rand::Rng;
+
+/// Continuously spawns particles at a fixed rate, giving them a finite
+/// `life` and an optional constant acceleration, so a cloud can flow
+/// (fountains, jets) instead of sitting static forever.
+pub struct Emitter {
+    pub position: [f32; 3],
+    /// Particles spawned per second.
+    pub spawn_rate: f32,
+    /// Lifetime, in seconds, assigned to each spawned particle.
+    pub life: f32,
+    pub charge_dist: Normal,
+    /// Per-axis jitter added to `position` for each spawned particle, so a
+    /// wide emitter doesn't spawn every particle on exactly the same point.
+    pub position_spread: Uniform<f32>,
+    /// Per-axis initial velocity distribution, sampled independently for
+    /// x/y/z. A `Normal` centered away from zero turns the fountain into a
+    /// directional jet.
+    pub velocity_dist: Normal,
+    pub mass: f32,
+    /// Constant acceleration (e.g. gravity) applied to every particle this
+    /// emitter spawns, for the lifetime of that particle.
+    pub gravity: Option<[f32; 3]>,
+    /// Fractional particles owed since the last spawn, carried over frame
+    /// to frame so `spawn_rate` is honored on average regardless of the
+    /// timestep.
+    accumulated: f32,
+}
+
+impl Emitter {
+    pub fn new(
+        position: [f32; 3],
+        spawn_rate: f32,
+        life: f32,
+        charge_dist: Normal,
+        position_spread: Uniform<f32>,
+        velocity_dist: Normal,
+        mass: f32,
+        gravity: Option<[f32; 3]>,
+    ) -> Self {
+        Emitter {
+            position,
+            spawn_rate,
+            life,
+            charge_dist,
+            position_spread,
+            velocity_dist,
+            mass,
+            gravity,
+            accumulated: 0.0,
+        }
+    }
+
+    /// Advances the spawn schedule by `dt` seconds, pushing any newly due
+    /// particles onto `particles`.
+    pub fn update<R: Rng>(&mut self, dt: f32, rng: &mut R, particles: &mut Vec<Particle>) {
+        self.accumulated += self.spawn_rate * dt;
+        while self.accumulated >= 1.0 {
+            self.accumulated -= 1.0;
+            particles.push(self.spawn(rng));
+        }
+    }
+
+    fn spawn<R: Rng>(&self, rng: &mut R) -> Particle {
+        Particle {
+            mass: self.mass,
+            charge: self.charge_dist.sample(rng) as f32,
+            velocity: [
+                self.velocity_dist.sample(rng) as f32,
+                self.velocity_dist.sample(rng) as f32,
+                self.velocity_dist.sample(rng) as f32,
+            ],
+            position: [
+                self.position[0] + self.position_spread.sample(rng),
+                self.position[1] + self.position_spread.sample(rng),
+                self.position[2] + self.position_spread.sample(rng),
+            ],
+            life: self.life,
+            acceleration: self.gravity.unwrap_or([0.0, 0.0, 0.0]),
+        }
+    }
+}
@@ -1,64 +1,109 @@
-use glium::{glutin, Surface};
+mod barnes_hut;
+mod camera;
+mod controls;
+mod emitter;
+mod simulator;
+
+use camera::Camera;
+use controls::Controls;
+use emitter::Emitter;
+use glam::Mat4;
+use glium::{glutin, uniform, Surface};
 use rand::distributions::*;
 use rand::Rng;
+use simulator::{BarnesHutSim, CpuSim, GpuSim, Simulator};
+
+/// Fixed simulation timestep, in seconds, used to decay particle lifetimes
+/// and to apply emitter gravity in the motion step.
+pub const TIME_STEP: f32 = 1.0 / 60.0;
+
+/// Default Plummer softening length for `coulomb_force`. Keeps the force
+/// finite when two particles coincide instead of dividing by zero.
+pub const DEFAULT_EPS: f32 = 0.05;
+
+/// Charge magnitude given to a particle spawned by a left-click; the sign
+/// comes from `Controls::charge_sign`.
+pub const SPAWN_CHARGE: f32 = 0.03;
+
+/// Lifetime, in seconds, of a left-click-spawned particle.
+pub const SPAWN_LIFE: f32 = 6.0;
+
+/// Charge of the right-click-drag attractor, large enough to dominate the
+/// local field it's dropped into.
+pub const ATTRACTOR_CHARGE: f32 = 5.0;
+
+/// Charge magnitude at which a sprite reaches full brightness; the typical
+/// cloud/emitter charge is a couple of percent of this, the attractor is
+/// several times over it and simply saturates.
+pub const CHARGE_BRIGHTNESS_SCALE: f32 = 0.1;
 
 #[derive(Copy, Clone)]
-struct Particle {
+pub struct Particle {
     pub mass: f32,
     pub charge: f32,
-    pub velocity: [f32; 2],
-    pub position: [f32; 2],
+    pub velocity: [f32; 3],
+    pub position: [f32; 3],
+    /// Seconds remaining before this particle is recycled. Particles
+    /// spawned outside of an `Emitter` (e.g. the initial cloud) can set
+    /// this to `f32::INFINITY` to live forever.
+    pub life: f32,
+    /// Constant acceleration applied every motion step, e.g. the gravity
+    /// an `Emitter` assigns to the particles it spawns.
+    pub acceleration: [f32; 3],
 }
 
-fn magnitude(value: [f32; 2]) -> f32 {
-    ((value[0] * value[0]) + (value[1] * value[1])).sqrt()
+pub fn magnitude(value: [f32; 3]) -> f32 {
+    ((value[0] * value[0]) + (value[1] * value[1]) + (value[2] * value[2])).sqrt()
 }
 
-fn normalize(value: [f32; 2]) -> [f32; 2] {
-    let magnitude = magnitude(value);
-    return [value[0] / magnitude, value[1] / magnitude];
+pub fn subtract(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
 }
 
-fn subtract(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
-    [a[0] - b[0], a[1] - b[1]]
+pub fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
 }
 
-fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
-    [a[0] + b[0], a[1] + b[1]]
+pub fn scalar_mul(a: [f32; 3], b: f32) -> [f32; 3] {
+    [a[0] * b, a[1] * b, a[2] * b]
 }
 
-fn scalar_mul(a: [f32; 2], b: f32) -> [f32; 2] {
-    [a[0] * b, a[1] * b]
+/// Coulomb force that `charge` at `position` feels from `other_charge` at
+/// `other_position`, using Plummer softening so it stays finite as the two
+/// positions coincide: `q1*q2 / (r^2 + eps^2)^1.5 * (x1 - x2)`.
+pub fn coulomb_force(
+    position: [f32; 3],
+    charge: f32,
+    other_position: [f32; 3],
+    other_charge: f32,
+    eps: f32,
+) -> [f32; 3] {
+    let line_segment = subtract(position, other_position);
+    let r2 = line_segment[0] * line_segment[0]
+        + line_segment[1] * line_segment[1]
+        + line_segment[2] * line_segment[2];
+    let denom = (r2 + eps * eps).powf(1.5);
+    scalar_mul(line_segment, (charge * other_charge) / denom)
 }
 
-impl Particle {
-    fn simulate_motion_step(&mut self) {
-        self.position = add(self.position, self.velocity);
-    }
-
-    fn simulate_force(&mut self, other: &Particle) {
-        let line_segment = subtract(self.position, other.position);
-        let r = magnitude(line_segment);
-        let cpd = (self.charge * other.charge) / (r * r);
-        let unit_direction = normalize(line_segment);
-        let f = scalar_mul(unit_direction, cpd / self.mass);
-        self.velocity = add(self.velocity, f);
-    }
-}
-
-fn particle_sim(particles: &mut Vec<Particle>) {
-    for x in 0..particles.len() {
-        let current_particle = particles[x].clone();
-        for y in (0..x).chain(x + 1..particles.len()) {
-            particles[y].simulate_force(&current_particle);
+/// Decrements every particle's remaining life and recycles the ones that
+/// have expired with an O(1) swap-remove: the last live particle is copied
+/// over the dead slot and the live count shrinks, so there are never gaps.
+fn decay_particles(particles: &mut Vec<Particle>, dt: f32) {
+    let mut i = 0;
+    while i < particles.len() {
+        particles[i].life -= dt;
+        if particles[i].life <= 0.0 {
+            particles.swap_remove(i);
+        } else {
+            i += 1;
         }
-        particles[x].simulate_motion_step();
     }
 }
 
 #[derive(Copy, Clone)]
 struct Vertex {
-    position: [f32; 2],
+    position: [f32; 3],
     color: [f32; 3],
 }
 
@@ -74,34 +119,84 @@ fn main() {
         particles.push(Particle {
             mass: 100000.0,
             charge: charge_dist.sample(&mut rng) as f32,
-            velocity: [0.0, 0.0],
+            velocity: [0.0, 0.0, 0.0],
             position: [
                 position_dist.sample(&mut rng) as f32,
                 position_dist.sample(&mut rng) as f32,
+                position_dist.sample(&mut rng) as f32,
             ],
+            life: f32::INFINITY,
+            acceleration: [0.0, 0.0, 0.0],
         })
     }
 
+    let mut emitters = vec![Emitter::new(
+        [0.0, -0.9, 0.0],
+        60.0,
+        3.0,
+        Normal::new(0.0, 0.03),
+        Uniform::new(-0.02, 0.02),
+        Normal::new(0.0, 0.1),
+        100000.0,
+        Some([0.0, 0.4, 0.0]),
+    )];
+
     let mut events_loop = glutin::EventsLoop::new();
     let wb = glutin::WindowBuilder::new();
-    let cb = glutin::ContextBuilder::new();
+    let cb = glutin::ContextBuilder::new().with_depth_buffer(24);
     let display = glium::Display::new(wb, cb, &events_loop).unwrap();
 
-    let vertex_buffer: glium::VertexBuffer<Vertex> =
-        glium::VertexBuffer::empty_dynamic(&display, particles.len()).unwrap();
     let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
     let mut params = glium::DrawParameters::default();
-    params.point_size = Some(8.0);
+    params.point_size = Some(24.0);
+    // Sprites blend additively so overlapping particles accumulate into a
+    // brighter glow instead of occluding each other, so depth writes are
+    // disabled; the test stays on so the glow field is still sorted behind
+    // anything opaque.
+    params.depth = glium::Depth {
+        test: glium::draw_parameters::DepthTest::IfLess,
+        write: false,
+        ..Default::default()
+    };
+    params.blend = glium::Blend {
+        color: glium::BlendingFunction::Addition {
+            source: glium::LinearBlendingFactor::SourceAlpha,
+            destination: glium::LinearBlendingFactor::One,
+        },
+        alpha: glium::BlendingFunction::Addition {
+            source: glium::LinearBlendingFactor::SourceAlpha,
+            destination: glium::LinearBlendingFactor::One,
+        },
+        ..Default::default()
+    };
+
+    // Radial-gradient glow sprite, sampled with `gl_PointCoord` so each
+    // point drawn below becomes a soft textured billboard instead of a flat
+    // square.
+    let sprite_image = image::load(
+        std::io::Cursor::new(&include_bytes!("../assets/particle_glow.png")[..]),
+        image::ImageFormat::Png,
+    )
+    .unwrap()
+    .to_rgba8();
+    let sprite_dimensions = sprite_image.dimensions();
+    let sprite_raw =
+        glium::texture::RawImage2d::from_raw_rgba_reversed(&sprite_image.into_raw(), sprite_dimensions);
+    let sprite_texture = glium::texture::Texture2d::new(&display, sprite_raw).unwrap();
 
     let vertex_shader_src = r#"
         #version 140
 
-        in vec2 position;
+        uniform mat4 perspective;
+        uniform mat4 view;
+        uniform mat4 model;
+
+        in vec3 position;
         in vec3 color;
         out vec3 vcolor;
 
         void main() {
-            gl_Position = vec4(position, 0.0, 1.0);
+            gl_Position = perspective * view * model * vec4(position, 1.0);
             vcolor = color;
         }
     "#;
@@ -109,11 +204,14 @@ fn main() {
     let fragment_shader_src = r#"
         #version 140
 
+        uniform sampler2D sprite_tex;
+
         out vec4 color;
         in vec3 vcolor;
 
         void main() {
-            color = vec4(vcolor, 1.0);
+            vec4 sprite = texture(sprite_tex, gl_PointCoord);
+            color = vec4(vcolor * sprite.rgb, sprite.a);
         }
     "#;
 
@@ -121,39 +219,186 @@ fn main() {
         glium::Program::from_source(&display, vertex_shader_src, fragment_shader_src, None)
         .unwrap();
 
+    let mut camera = Camera::new();
+    let mut controls = Controls::new();
+    // The right-click-drag attractor, held outside `particles` by value
+    // rather than by index: `decay_particles` recycles dead particles with
+    // a swap-remove, which would silently relocate (or desync) a tracked
+    // index. Kept separate, it's immune to that — it's merged into
+    // `particles` just long enough for each physics step to feel its pull,
+    // then popped back out, until the drag ends and it's pushed in for
+    // good.
+    let mut attractor: Option<Particle> = None;
+    let mut dragging_attractor = false;
+
+    // Three interchangeable backends, in increasing order of scale: `CpuSim`
+    // is the exact O(n^2) reference, `BarnesHutSim` approximates it in
+    // O(n log n), and `GpuSim` moves the exact sum onto the GPU. `GpuSim`
+    // reallocates its textures on demand (see `GpuSim::ensure_capacity`),
+    // so it's just as compatible with the emitter growing/shrinking the
+    // live set as the CPU-side backends.
+    enum SimBackend {
+        Cpu,
+        BarnesHut,
+        Gpu,
+    }
+    // Runtime-selectable via `CHARGE_TOY_BACKEND=cpu|barnes_hut|gpu` so a
+    // backend comparison doesn't require recompiling.
+    let sim_backend = match std::env::var("CHARGE_TOY_BACKEND").as_deref() {
+        Ok("cpu") => SimBackend::Cpu,
+        Ok("gpu") => SimBackend::Gpu,
+        _ => SimBackend::BarnesHut,
+    };
+    let mut simulator: Box<dyn Simulator> = match sim_backend {
+        SimBackend::Cpu => Box::new(CpuSim::default()),
+        SimBackend::BarnesHut => Box::new(BarnesHutSim::default()),
+        SimBackend::Gpu => Box::new(GpuSim::new(&display, particles.len())),
+    };
+
     let mut closed = false;
     while !closed {
-        particle_sim(&mut particles);
+        let (width, height) = display.get_framebuffer_dimensions();
+        let aspect = width as f32 / height.max(1) as f32;
+        let perspective = Mat4::perspective_rh_gl(
+            std::f32::consts::FRAC_PI_4,
+            aspect,
+            0.1,
+            100.0,
+        );
+        let view = camera.view_matrix();
+        // Used to turn a cursor position into a world-space point on the
+        // camera's target plane; computed once per frame and reused for
+        // both the attractor pin below and mouse clicks in the event loop.
+        let inverse_view_proj = (perspective * view).inverse();
+
+        // While dragging, the attractor is pinned to the cursor every
+        // frame (not just on `CursorMoved`) so it doesn't drift under
+        // Coulomb forces from the cloud while the mouse sits still.
+        if dragging_attractor {
+            if let Some(particle) = &mut attractor {
+                particle.position =
+                    controls.cursor_world(width, height, inverse_view_proj, camera.target.z);
+                particle.velocity = [0.0, 0.0, 0.0];
+            }
+        }
 
-        let mut display_points = Vec::with_capacity(particles.len());
-        for particle in &particles {
+        // The attractor is merged into `particles` only for the duration
+        // of the physics step, so it still pulls on (and is pulled by) the
+        // cloud; while dragging its simulated result is discarded in
+        // favor of the cursor pin above, so it can't fly off from the
+        // reaction force.
+        if let Some(particle) = attractor {
+            particles.push(particle);
+        }
+        simulator.step(&mut particles);
+        if attractor.is_some() {
+            let simulated = particles.pop().expect("attractor was pushed above");
+            if !dragging_attractor {
+                attractor = Some(simulated);
+            }
+        }
+        decay_particles(&mut particles, TIME_STEP);
+        for emitter in &mut emitters {
+            emitter.update(TIME_STEP, &mut rng, &mut particles);
+        }
+
+        let mut display_points = Vec::with_capacity(particles.len() + 1);
+        for particle in particles.iter().chain(attractor.iter()) {
+            // Sign picks a hue (warm for positive, cool for negative);
+            // magnitude picks brightness, so dense same-sign clusters glow
+            // brighter once the additive blending above stacks them.
+            let brightness = (particle.charge.abs() / CHARGE_BRIGHTNESS_SCALE).min(1.0);
+            let color = if particle.charge > 0.0 {
+                [brightness, 0.3 * brightness, 0.1 * brightness]
+            } else {
+                [0.1 * brightness, 0.3 * brightness, brightness]
+            };
             display_points.push(Vertex {
                 position: particle.position,
-                color: if particle.charge > 0.0 {
-                    [1.0, 0.2, 0.2]
-                } else {
-                    [0.2, 0.4, 1.0]
-                },
+                color,
             });
         }
-        vertex_buffer.write(&display_points);
+        let vertex_buffer: glium::VertexBuffer<Vertex> =
+            glium::VertexBuffer::new(&display, &display_points).unwrap();
+
+        let uniforms = uniform! {
+            perspective: perspective.to_cols_array_2d(),
+            view: view.to_cols_array_2d(),
+            model: Mat4::IDENTITY.to_cols_array_2d(),
+            sprite_tex: sprite_texture.sampled(),
+        };
 
         let mut target = display.draw();
-        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
         target
-            .draw(
-                &vertex_buffer,
-                &indices,
-                &program,
-                &glium::uniforms::EmptyUniforms,
-                &params,
-                )
+            .draw(&vertex_buffer, &indices, &program, &uniforms, &params)
             .unwrap();
         target.finish().unwrap();
 
         events_loop.poll_events(|event| match event {
             glutin::Event::WindowEvent { event, .. } => match event {
                 glutin::WindowEvent::CloseRequested => closed = true,
+                glutin::WindowEvent::CursorMoved { position, .. } => {
+                    camera.handle_cursor_moved(position.x, position.y);
+                    controls.handle_cursor_moved(position.x, position.y);
+                }
+                // Left and right are spoken for by particle injection and
+                // the attractor below, so the orbit camera moved to a
+                // middle-mouse drag.
+                glutin::WindowEvent::MouseInput {
+                    state,
+                    button: glutin::MouseButton::Middle,
+                    ..
+                } => {
+                    camera.set_dragging(state == glutin::ElementState::Pressed);
+                }
+                glutin::WindowEvent::MouseInput {
+                    state: glutin::ElementState::Pressed,
+                    button: glutin::MouseButton::Left,
+                    ..
+                } => {
+                    particles.push(Particle {
+                        mass: 100000.0,
+                        charge: controls.charge_sign * SPAWN_CHARGE,
+                        velocity: [0.0, 0.0, 0.0],
+                        position: controls.cursor_world(width, height, inverse_view_proj, camera.target.z),
+                        life: SPAWN_LIFE,
+                        acceleration: [0.0, 0.0, 0.0],
+                    });
+                }
+                glutin::WindowEvent::MouseInput {
+                    state,
+                    button: glutin::MouseButton::Right,
+                    ..
+                } => match state {
+                    glutin::ElementState::Pressed => {
+                        attractor = Some(Particle {
+                            mass: 100000.0,
+                            charge: ATTRACTOR_CHARGE,
+                            velocity: [0.0, 0.0, 0.0],
+                            position: controls.cursor_world(width, height, inverse_view_proj, camera.target.z),
+                            life: f32::INFINITY,
+                            acceleration: [0.0, 0.0, 0.0],
+                        });
+                        dragging_attractor = true;
+                    }
+                    glutin::ElementState::Released => {
+                        dragging_attractor = false;
+                        if let Some(particle) = attractor.take() {
+                            particles.push(particle);
+                        }
+                    }
+                },
+                glutin::WindowEvent::KeyboardInput { input, .. } => {
+                    controls.set_charge_sign(input.modifiers.shift);
+                }
+                glutin::WindowEvent::MouseWheel { delta, .. } => {
+                    let scroll = match delta {
+                        glutin::MouseScrollDelta::LineDelta(_, y) => y,
+                        glutin::MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                    };
+                    camera.handle_scroll(scroll);
+                }
                 _ => (),
             },
             _ => (),
@@ -0,0 +1,552 @@
+use crate::barnes_hut::{BarnesHutTree, DEFAULT_THETA};
+use crate::{add, coulomb_force, scalar_mul, Particle, DEFAULT_EPS, TIME_STEP};
+use glium::texture::texture2d::Texture2d;
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, UncompressedFloatFormat};
+use glium::{uniform, Surface};
+
+/// Anything that can advance a particle cloud by one frame.
+///
+/// `CpuSim` is the exact O(n^2) reference, `BarnesHutSim` approximates the
+/// same force with a quadtree, and `GpuSim` offloads the exact sum to a
+/// fragment-shader pass so the toy can scale past a few thousand particles.
+pub trait Simulator {
+    fn step(&mut self, particles: &mut Vec<Particle>);
+}
+
+/// Advances `particles` with symplectic velocity-Verlet integration:
+/// `v += 0.5*a*dt`, `x += v*dt`, recompute `a` from the new positions,
+/// `v += 0.5*a_new*dt`. Unlike updating one particle's velocity mid-loop
+/// from a source particle that then also moves, this keeps the whole step
+/// order-independent and stable. `compute_accel` supplies the net
+/// acceleration on every particle from everyone else (brute-force or
+/// Barnes-Hut); any per-particle constant acceleration (e.g. emitter
+/// gravity) is added on top.
+fn velocity_verlet(particles: &mut Vec<Particle>, dt: f32, compute_accel: impl Fn(&[Particle]) -> Vec<[f32; 3]>) {
+    let a0 = compute_accel(particles);
+    for (particle, &a) in particles.iter_mut().zip(&a0) {
+        let total_a = add(a, particle.acceleration);
+        particle.velocity = add(particle.velocity, scalar_mul(total_a, 0.5 * dt));
+        particle.position = add(particle.position, scalar_mul(particle.velocity, dt));
+    }
+    let a1 = compute_accel(particles);
+    for (particle, &a) in particles.iter_mut().zip(&a1) {
+        let total_a = add(a, particle.acceleration);
+        particle.velocity = add(particle.velocity, scalar_mul(total_a, 0.5 * dt));
+    }
+}
+
+/// Net Plummer-softened Coulomb acceleration on every particle from every
+/// other particle, computed exactly in O(n^2).
+fn brute_force_accelerations(particles: &[Particle], eps: f32) -> Vec<[f32; 3]> {
+    let mut accel = vec![[0.0, 0.0, 0.0]; particles.len()];
+    for i in 0..particles.len() {
+        for j in 0..particles.len() {
+            if i == j {
+                continue;
+            }
+            let force = coulomb_force(
+                particles[i].position,
+                particles[i].charge,
+                particles[j].position,
+                particles[j].charge,
+                eps,
+            );
+            accel[i] = add(accel[i], scalar_mul(force, 1.0 / particles[i].mass));
+        }
+    }
+    accel
+}
+
+/// The exact O(n^2) reference simulator.
+pub struct CpuSim {
+    pub eps: f32,
+}
+
+impl Default for CpuSim {
+    fn default() -> Self {
+        CpuSim { eps: DEFAULT_EPS }
+    }
+}
+
+impl Simulator for CpuSim {
+    fn step(&mut self, particles: &mut Vec<Particle>) {
+        velocity_verlet(particles, TIME_STEP, |ps| brute_force_accelerations(ps, self.eps));
+    }
+}
+
+/// Approximates the force sum with a Barnes-Hut quadtree, rebuilt from
+/// scratch every frame, trading exactness (controlled by `theta`) for
+/// O(n log n) scaling instead of `CpuSim`'s O(n^2).
+pub struct BarnesHutSim {
+    pub theta: f32,
+    pub eps: f32,
+}
+
+impl Default for BarnesHutSim {
+    fn default() -> Self {
+        BarnesHutSim { theta: DEFAULT_THETA, eps: DEFAULT_EPS }
+    }
+}
+
+impl Simulator for BarnesHutSim {
+    fn step(&mut self, particles: &mut Vec<Particle>) {
+        let theta = self.theta;
+        let eps = self.eps;
+        velocity_verlet(particles, TIME_STEP, |ps| {
+            let tree = BarnesHutTree::build(ps, theta, eps);
+            ps.iter()
+                .map(|p| scalar_mul(tree.force_on(p), 1.0 / p.mass))
+                .collect()
+        });
+    }
+}
+
+#[derive(Copy, Clone)]
+struct QuadVertex {
+    position: [f32; 2],
+}
+glium::implement_vertex!(QuadVertex, position);
+
+/// Runs the Coulomb-force accumulation and motion integration on the GPU,
+/// using the same two-phase velocity-Verlet scheme as `velocity_verlet`:
+/// half-kick, drift, recompute acceleration at the new positions, then the
+/// second half-kick. Each phase is one full-screen-quad pass over a
+/// one-texel-per-particle texture; `state_tex` holds (x, y, z, charge), the
+/// velocity textures hold (vx, vy, vz, mass), and `gravity_tex` holds each
+/// particle's constant `acceleration` (e.g. emitter gravity), added in
+/// during `program_accel` so it lands in both half-kicks just like
+/// `velocity_verlet` adding `particle.acceleration` on the CPU side.
+/// Textures are reallocated in `ensure_capacity` whenever the live particle
+/// count changes, so this stays correct as an `Emitter` grows or shrinks the
+/// set.
+pub struct GpuSim {
+    width: u32,
+    pub eps: f32,
+    display: glium::Display,
+    program_accel: glium::Program,
+    program_kick: glium::Program,
+    program_drift: glium::Program,
+    quad_vertices: glium::VertexBuffer<QuadVertex>,
+    quad_indices: glium::index::NoIndices,
+    /// Positions + charge, re-uploaded from the CPU-side particles every
+    /// frame since they are the source of truth.
+    state_tex: Texture2d,
+    /// Velocities + mass, re-uploaded every frame (mass never changes, but
+    /// this also seeds the velocity the first half-kick starts from).
+    velocity_tex: Texture2d,
+    /// Per-particle constant acceleration (e.g. emitter gravity), re-uploaded
+    /// every frame like `state_tex`/`velocity_tex`. Added into `accel_tex` by
+    /// `program_accel` so it lands in both half-kicks, matching how
+    /// `velocity_verlet` adds `particle.acceleration` on top of the computed
+    /// Coulomb acceleration on the CPU side.
+    gravity_tex: Texture2d,
+    /// Scratch acceleration buffer, overwritten by `compute_accel` once per
+    /// half-step and consumed by the following kick pass before its next
+    /// write.
+    accel_tex: Texture2d,
+    /// Velocity after the first half-kick, i.e. `v + 0.5*a0*dt`.
+    velocity_half_tex: Texture2d,
+    /// Position after the drift step that follows the first half-kick.
+    position_mid_tex: Texture2d,
+    /// Velocity after the second half-kick; this and `position_mid_tex` are
+    /// what gets read back into the CPU-side particles.
+    velocity_final_tex: Texture2d,
+}
+
+impl GpuSim {
+    pub fn new(display: &glium::Display, particle_count: usize) -> Self {
+        let width = particle_count as u32;
+        let [state_tex, velocity_tex, gravity_tex, accel_tex, velocity_half_tex, position_mid_tex, velocity_final_tex] =
+            Self::make_textures(display, width);
+
+        let quad_vertices = glium::VertexBuffer::new(
+            display,
+            &[
+                QuadVertex { position: [-1.0, -1.0] },
+                QuadVertex { position: [1.0, -1.0] },
+                QuadVertex { position: [-1.0, 1.0] },
+                QuadVertex { position: [1.0, 1.0] },
+            ],
+        )
+        .unwrap();
+        let quad_indices =
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+
+        let quad_vertex_shader = r#"
+            #version 140
+
+            in vec2 position;
+            out vec2 v_uv;
+
+            void main() {
+                v_uv = position * 0.5 + 0.5;
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "#;
+
+        // For each output texel (particle i), sum the Plummer-softened
+        // Coulomb contribution from every other particle in `pos_tex` and
+        // divide by `i`'s own mass (read from `vel_tex`, which never
+        // changes within a frame) to get a net acceleration.
+        let accel_fragment_shader = r#"
+            #version 140
+
+            uniform sampler2D pos_tex;
+            uniform sampler2D vel_tex;
+            uniform sampler2D gravity_tex;
+            uniform int width;
+            uniform float eps;
+
+            in vec2 v_uv;
+            out vec4 accel;
+
+            void main() {
+                int i = int(v_uv.x * float(width));
+                vec4 me = texelFetch(pos_tex, ivec2(i, 0), 0);
+                float mass = texelFetch(vel_tex, ivec2(i, 0), 0).w;
+                vec3 a = vec3(0.0);
+
+                for (int j = 0; j < width; j++) {
+                    if (j == i) {
+                        continue;
+                    }
+                    vec4 other = texelFetch(pos_tex, ivec2(j, 0), 0);
+                    vec3 line_segment = me.xyz - other.xyz;
+                    float r2 = dot(line_segment, line_segment);
+                    float denom = pow(r2 + eps * eps, 1.5);
+                    float cpd = (me.w * other.w) / denom;
+                    a += line_segment * (cpd / mass);
+                }
+
+                vec3 gravity = texelFetch(gravity_tex, ivec2(i, 0), 0).xyz;
+                accel = vec4(a + gravity, 0.0);
+            }
+        "#;
+
+        // `v += 0.5*a*dt`, used for both the first and second half-kick;
+        // which velocity/accel textures feed it is chosen by the caller.
+        let kick_fragment_shader = r#"
+            #version 140
+
+            uniform sampler2D vel_tex;
+            uniform sampler2D accel_tex;
+            uniform int width;
+            uniform float dt;
+
+            in vec2 v_uv;
+            out vec4 new_velocity;
+
+            void main() {
+                int i = int(v_uv.x * float(width));
+                vec4 vel = texelFetch(vel_tex, ivec2(i, 0), 0);
+                vec3 a = texelFetch(accel_tex, ivec2(i, 0), 0).xyz;
+                new_velocity = vec4(vel.xyz + 0.5 * a * dt, vel.w);
+            }
+        "#;
+
+        // `x += v*dt`, applied once between the two half-kicks.
+        let drift_fragment_shader = r#"
+            #version 140
+
+            uniform sampler2D pos_tex;
+            uniform sampler2D vel_tex;
+            uniform int width;
+            uniform float dt;
+
+            in vec2 v_uv;
+            out vec4 new_position;
+
+            void main() {
+                int i = int(v_uv.x * float(width));
+                vec4 pos = texelFetch(pos_tex, ivec2(i, 0), 0);
+                vec3 vel = texelFetch(vel_tex, ivec2(i, 0), 0).xyz;
+                new_position = vec4(pos.xyz + vel * dt, pos.w);
+            }
+        "#;
+
+        let program_accel = glium::Program::from_source(
+            display,
+            quad_vertex_shader,
+            accel_fragment_shader,
+            None,
+        )
+        .unwrap();
+        let program_kick = glium::Program::from_source(
+            display,
+            quad_vertex_shader,
+            kick_fragment_shader,
+            None,
+        )
+        .unwrap();
+        let program_drift = glium::Program::from_source(
+            display,
+            quad_vertex_shader,
+            drift_fragment_shader,
+            None,
+        )
+        .unwrap();
+
+        GpuSim {
+            width,
+            eps: DEFAULT_EPS,
+            display: display.clone(),
+            program_accel,
+            program_kick,
+            program_drift,
+            quad_vertices,
+            quad_indices,
+            state_tex,
+            velocity_tex,
+            gravity_tex,
+            accel_tex,
+            velocity_half_tex,
+            position_mid_tex,
+            velocity_final_tex,
+        }
+    }
+
+    fn make_textures(display: &glium::Display, width: u32) -> [Texture2d; 7] {
+        let make = || {
+            Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::F32F32F32F32,
+                MipmapsOption::NoMipmap,
+                width.max(1),
+                1,
+            )
+            .unwrap()
+        };
+        [make(), make(), make(), make(), make(), make(), make()]
+    }
+
+    /// Reallocates every texture to `width` texels when the live particle
+    /// count has changed since the last frame, so the emitter growing or
+    /// shrinking the set (or mouse-spawned particles) doesn't read or write
+    /// past the end of a texture sized for a stale count.
+    fn ensure_capacity(&mut self, width: u32) {
+        if width == self.width {
+            return;
+        }
+        self.width = width;
+        let [state_tex, velocity_tex, gravity_tex, accel_tex, velocity_half_tex, position_mid_tex, velocity_final_tex] =
+            Self::make_textures(&self.display, width);
+        self.state_tex = state_tex;
+        self.velocity_tex = velocity_tex;
+        self.gravity_tex = gravity_tex;
+        self.accel_tex = accel_tex;
+        self.velocity_half_tex = velocity_half_tex;
+        self.position_mid_tex = position_mid_tex;
+        self.velocity_final_tex = velocity_final_tex;
+    }
+
+    fn upload_state(&self, particles: &[Particle]) {
+        let data: Vec<[f32; 4]> = particles
+            .iter()
+            .map(|p| [p.position[0], p.position[1], p.position[2], p.charge])
+            .collect();
+        Self::write_texture(&self.state_tex, self.width, &data);
+    }
+
+    fn upload_velocity(&self, particles: &[Particle]) {
+        let data: Vec<[f32; 4]> = particles
+            .iter()
+            .map(|p| [p.velocity[0], p.velocity[1], p.velocity[2], p.mass])
+            .collect();
+        Self::write_texture(&self.velocity_tex, self.width, &data);
+    }
+
+    fn upload_gravity(&self, particles: &[Particle]) {
+        let data: Vec<[f32; 4]> = particles
+            .iter()
+            .map(|p| [p.acceleration[0], p.acceleration[1], p.acceleration[2], 0.0])
+            .collect();
+        Self::write_texture(&self.gravity_tex, self.width, &data);
+    }
+
+    fn write_texture(tex: &Texture2d, width: u32, data: &[[f32; 4]]) {
+        let raw = RawImage2d {
+            data: std::borrow::Cow::Owned(
+                data.iter().flat_map(|v| v.iter().copied()).collect(),
+            ),
+            width: width.max(1),
+            height: 1,
+            format: ClientFormat::F32F32F32F32,
+        };
+        tex.write(
+            glium::Rect {
+                left: 0,
+                bottom: 0,
+                width: width.max(1),
+                height: 1,
+            },
+            raw,
+        );
+    }
+
+    /// Writes the net acceleration at `pos_tex` (using `self.velocity_tex`
+    /// only for the invariant per-particle mass) into `self.accel_tex`.
+    fn compute_accel(&self, pos_tex: &Texture2d) {
+        let mut framebuffer =
+            glium::framebuffer::SimpleFrameBuffer::new(&self.display, &self.accel_tex).unwrap();
+        let uniforms = uniform! {
+            pos_tex: pos_tex.sampled(),
+            vel_tex: self.velocity_tex.sampled(),
+            gravity_tex: self.gravity_tex.sampled(),
+            width: self.width as i32,
+            eps: self.eps,
+        };
+        framebuffer
+            .draw(
+                &self.quad_vertices,
+                &self.quad_indices,
+                &self.program_accel,
+                &uniforms,
+                &Default::default(),
+            )
+            .unwrap();
+    }
+
+    /// Applies a half-kick (`v += 0.5*a*dt`) from `vel_tex` and
+    /// `self.accel_tex` into `dest`.
+    fn kick(&self, vel_tex: &Texture2d, dest: &Texture2d) {
+        let mut framebuffer =
+            glium::framebuffer::SimpleFrameBuffer::new(&self.display, dest).unwrap();
+        let uniforms = uniform! {
+            vel_tex: vel_tex.sampled(),
+            accel_tex: self.accel_tex.sampled(),
+            width: self.width as i32,
+            dt: TIME_STEP,
+        };
+        framebuffer
+            .draw(
+                &self.quad_vertices,
+                &self.quad_indices,
+                &self.program_kick,
+                &uniforms,
+                &Default::default(),
+            )
+            .unwrap();
+    }
+
+    /// Drifts `self.state_tex` by `self.velocity_half_tex * dt` into
+    /// `self.position_mid_tex`.
+    fn drift(&self) {
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(
+            &self.display,
+            &self.position_mid_tex,
+        )
+        .unwrap();
+        let uniforms = uniform! {
+            pos_tex: self.state_tex.sampled(),
+            vel_tex: self.velocity_half_tex.sampled(),
+            width: self.width as i32,
+            dt: TIME_STEP,
+        };
+        framebuffer
+            .draw(
+                &self.quad_vertices,
+                &self.quad_indices,
+                &self.program_drift,
+                &uniforms,
+                &Default::default(),
+            )
+            .unwrap();
+    }
+}
+
+impl Simulator for GpuSim {
+    fn step(&mut self, particles: &mut Vec<Particle>) {
+        if particles.is_empty() {
+            return;
+        }
+        self.ensure_capacity(particles.len() as u32);
+        self.upload_state(particles);
+        self.upload_velocity(particles);
+        self.upload_gravity(particles);
+
+        // Same two-phase update as `velocity_verlet`, just spread across
+        // GPU passes instead of CPU loops.
+        self.compute_accel(&self.state_tex);
+        self.kick(&self.velocity_tex, &self.velocity_half_tex);
+        self.drift();
+        self.compute_accel(&self.position_mid_tex);
+        self.kick(&self.velocity_half_tex, &self.velocity_final_tex);
+
+        // `Texture2d::read` is hard-coded to `U8U8U8U8`; these textures are
+        // `F32F32F32F32`, so read back through `unchecked_read` instead.
+        let positions: Vec<Vec<(f32, f32, f32, f32)>> =
+            unsafe { self.position_mid_tex.unchecked_read() };
+        let velocities: Vec<Vec<(f32, f32, f32, f32)>> =
+            unsafe { self.velocity_final_tex.unchecked_read() };
+        for (i, particle) in particles.iter_mut().enumerate() {
+            let (x, y, z, _) = positions[0][i];
+            let (vx, vy, vz, _) = velocities[0][i];
+            particle.position = [x, y, z];
+            particle.velocity = [vx, vy, vz];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subtract;
+
+    fn total_momentum(particles: &[Particle]) -> [f32; 3] {
+        particles.iter().fold([0.0, 0.0, 0.0], |total, p| {
+            add(total, scalar_mul(p.velocity, p.mass))
+        })
+    }
+
+    // Unequal masses, asymmetric positions, nonzero initial velocities, and
+    // mixed charge signs (so some pairs attract and some repel) give a
+    // non-trivial net drift in total momentum unless every pairwise force
+    // is accumulated before anything moves and applied symmetrically. A
+    // two-equal-charges-at-rest case doesn't exercise this: it starts and
+    // stays at ~0 momentum under essentially any integrator, including the
+    // order-dependent one this request replaced.
+    #[test]
+    fn cpu_sim_conserves_momentum_for_unequal_masses_and_asymmetric_charges() {
+        let mut particles = vec![
+            Particle {
+                mass: 1.0,
+                charge: 1.0,
+                velocity: [0.3, -0.1, 0.0],
+                position: [-0.6, 0.05, 0.1],
+                life: f32::INFINITY,
+                acceleration: [0.0, 0.0, 0.0],
+            },
+            Particle {
+                mass: 3.0,
+                charge: -1.0,
+                velocity: [-0.1, 0.2, 0.05],
+                position: [0.4, -0.3, -0.2],
+                life: f32::INFINITY,
+                acceleration: [0.0, 0.0, 0.0],
+            },
+            Particle {
+                mass: 0.4,
+                charge: 1.0,
+                velocity: [0.0, 0.0, -0.2],
+                position: [0.1, 0.5, 0.3],
+                life: f32::INFINITY,
+                acceleration: [0.0, 0.0, 0.0],
+            },
+        ];
+        let mut sim = CpuSim::default();
+
+        let initial_momentum = total_momentum(&particles);
+        for _ in 0..500 {
+            sim.step(&mut particles);
+        }
+        let final_momentum = total_momentum(&particles);
+
+        let drift = crate::magnitude(subtract(final_momentum, initial_momentum));
+        assert!(
+            drift < 1e-3,
+            "momentum drifted from {:?} to {:?}",
+            initial_momentum,
+            final_momentum
+        );
+    }
+}